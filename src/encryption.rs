@@ -0,0 +1,186 @@
+use crate::error::HistoryToDriveError;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use std::fs;
+use std::path::Path;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+/// Default path for the persisted key when `HISTORY_TO_DRIVE_KEY_FILE`
+/// isn't set.
+const DEFAULT_KEY_FILE: &str = "history_to_drive.key";
+
+/// Whether `--encrypt` was passed or `HISTORY_TO_DRIVE_ENCRYPT` is set to a
+/// truthy value. Shared by the import and `search` code paths so they agree
+/// on when fields are encrypted at rest.
+pub fn requested(args: &[String]) -> bool {
+    args.iter().any(|a| a == "--encrypt")
+        || std::env::var("HISTORY_TO_DRIVE_ENCRYPT")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false)
+}
+
+/// Path to the key file: `HISTORY_TO_DRIVE_KEY_FILE` if set, otherwise
+/// `history_to_drive.key` in the current directory.
+pub fn key_file_path() -> String {
+    std::env::var("HISTORY_TO_DRIVE_KEY_FILE").unwrap_or_else(|_| DEFAULT_KEY_FILE.to_string())
+}
+
+/// Encrypts the `title`, `url`, and `host` fields with XChaCha20-Poly1305
+/// before they reach the store, so a shared or synced database never sees
+/// plaintext browsing history. One random key per install, persisted to a
+/// local key file; a fresh random nonce per field, prepended to the
+/// ciphertext and base64-encoded to fit the existing `varchar` columns.
+/// Mirrors atuin's `encryption.rs`.
+pub struct HistoryCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl HistoryCipher {
+    /// Loads the key from `key_path`, generating and persisting a new
+    /// random 256-bit key if the file doesn't exist yet.
+    pub fn load_or_create(key_path: &Path) -> Result<Self, HistoryToDriveError> {
+        let key_bytes = if key_path.exists() {
+            let encoded = fs::read_to_string(key_path).map_err(|e| {
+                HistoryToDriveError::Unexpected(format!("could not read key file: {}", e))
+            })?;
+            base64::decode(encoded.trim())
+                .map_err(|e| HistoryToDriveError::Unexpected(format!("invalid key file: {}", e)))?
+        } else {
+            let mut key = vec![0u8; KEY_LEN];
+            rand::thread_rng().fill_bytes(&mut key);
+            Self::create_key_file(key_path, &base64::encode(&key))?;
+            key
+        };
+
+        if key_bytes.len() != KEY_LEN {
+            return Err(HistoryToDriveError::Unexpected(format!(
+                "key file at {} does not contain a {}-byte key",
+                key_path.display(),
+                KEY_LEN
+            )));
+        }
+
+        let key = Key::from_slice(&key_bytes);
+        Ok(HistoryCipher {
+            cipher: XChaCha20Poly1305::new(key),
+        })
+    }
+
+    /// Creates `key_path` with owner-only access from the start, so the key
+    /// is never briefly readable by other local users or swept up by
+    /// whatever syncs the directory it lives in.
+    #[cfg(unix)]
+    fn create_key_file(key_path: &Path, contents: &str) -> Result<(), HistoryToDriveError> {
+        use std::fs::OpenOptions;
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(key_path)
+            .and_then(|mut f| f.write_all(contents.as_bytes()))
+            .map_err(|e| {
+                HistoryToDriveError::Unexpected(format!("could not write key file: {}", e))
+            })
+    }
+
+    #[cfg(not(unix))]
+    fn create_key_file(key_path: &Path, contents: &str) -> Result<(), HistoryToDriveError> {
+        fs::write(key_path, contents).map_err(|e| {
+            HistoryToDriveError::Unexpected(format!("could not write key file: {}", e))
+        })
+    }
+
+    /// Encrypts `plaintext`, returning the nonce prepended to the
+    /// ciphertext, base64-encoded.
+    pub fn encrypt_field(&self, plaintext: &str) -> Result<String, HistoryToDriveError> {
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = XNonce::from_slice(&nonce_bytes);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(nonce, plaintext.as_bytes())
+            .map_err(|e| HistoryToDriveError::Unexpected(format!("encryption failed: {}", e)))?;
+
+        let mut payload = nonce_bytes.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(base64::encode(payload))
+    }
+
+    /// Reverses `encrypt_field`, for reading encrypted rows back out.
+    pub fn decrypt_field(&self, encoded: &str) -> Result<String, HistoryToDriveError> {
+        let payload = base64::decode(encoded)
+            .map_err(|e| HistoryToDriveError::Unexpected(format!("invalid ciphertext: {}", e)))?;
+        if payload.len() < NONCE_LEN {
+            return Err(HistoryToDriveError::Unexpected(
+                "ciphertext shorter than nonce".to_string(),
+            ));
+        }
+        let (nonce_bytes, ciphertext) = payload.split_at(NONCE_LEN);
+        let nonce = XNonce::from_slice(nonce_bytes);
+
+        let plaintext = self
+            .cipher
+            .decrypt(nonce, ciphertext)
+            .map_err(|e| HistoryToDriveError::Unexpected(format!("decryption failed: {}", e)))?;
+        String::from_utf8(plaintext).map_err(|e| {
+            HistoryToDriveError::Unexpected(format!("decrypted field is not utf-8: {}", e))
+        })
+    }
+
+    /// Maximum plaintext byte length that still fits, once encrypted and
+    /// base64-encoded, in a `varchar(column_limit)` column.
+    pub fn max_plaintext_len(column_limit: usize) -> usize {
+        let max_payload_bytes = column_limit * 3 / 4;
+        max_payload_bytes.saturating_sub(NONCE_LEN + TAG_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> HistoryCipher {
+        let key = Key::from_slice(&[7u8; KEY_LEN]);
+        HistoryCipher {
+            cipher: XChaCha20Poly1305::new(key),
+        }
+    }
+
+    #[test]
+    fn encrypt_then_decrypt_round_trips() {
+        let cipher = cipher();
+        let encoded = cipher.encrypt_field("https://example.com/page").unwrap();
+        assert_eq!(cipher.decrypt_field(&encoded).unwrap(), "https://example.com/page");
+    }
+
+    #[test]
+    fn encrypting_the_same_plaintext_twice_differs() {
+        let cipher = cipher();
+        let a = cipher.encrypt_field("same input").unwrap();
+        let b = cipher.encrypt_field("same input").unwrap();
+        assert_ne!(a, b, "nonce should be fresh per call");
+    }
+
+    #[test]
+    fn decrypt_rejects_truncated_ciphertext() {
+        let cipher = cipher();
+        assert!(cipher.decrypt_field(&base64::encode([0u8; 4])).is_err());
+    }
+
+    #[test]
+    fn max_plaintext_len_leaves_room_for_nonce_and_tag_once_encoded() {
+        let limit = HistoryCipher::max_plaintext_len(1000);
+        let plaintext = "x".repeat(limit);
+        let cipher = cipher();
+        let encoded = cipher.encrypt_field(&plaintext).unwrap();
+        assert!(encoded.len() <= 1000);
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+
+pub enum HistoryToDriveError {
+    DeserializeError(calamine::DeError),
+    ExcelError(calamine::XlsxError),
+    CalamineError(calamine::Error),
+    SqlxError(sqlx::Error),
+    SqliteError(rusqlite::Error),
+    Unexpected(String),
+}
+
+impl From<calamine::DeError> for HistoryToDriveError {
+    fn from(error: calamine::DeError) -> Self {
+        HistoryToDriveError::DeserializeError(error)
+    }
+}
+
+impl From<calamine::XlsxError> for HistoryToDriveError {
+    fn from(error: calamine::XlsxError) -> Self {
+        HistoryToDriveError::ExcelError(error)
+    }
+}
+
+impl From<calamine::Error> for HistoryToDriveError {
+    fn from(error: calamine::Error) -> Self {
+        HistoryToDriveError::CalamineError(error)
+    }
+}
+
+impl From<sqlx::Error> for HistoryToDriveError {
+    fn from(error: sqlx::Error) -> Self {
+        HistoryToDriveError::SqlxError(error)
+    }
+}
+
+impl From<rusqlite::Error> for HistoryToDriveError {
+    fn from(error: rusqlite::Error) -> Self {
+        HistoryToDriveError::SqliteError(error)
+    }
+}
+
+impl fmt::Debug for HistoryToDriveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryToDriveError::DeserializeError(de) => de.fmt(f),
+            HistoryToDriveError::ExcelError(xlsx) => xlsx.fmt(f),
+            HistoryToDriveError::CalamineError(cal) => cal.fmt(f),
+            HistoryToDriveError::SqlxError(sqlx) => sqlx.fmt(f),
+            HistoryToDriveError::SqliteError(lite) => lite.fmt(f),
+            HistoryToDriveError::Unexpected(s) => s.fmt(f),
+        }
+    }
+}
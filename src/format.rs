@@ -0,0 +1,259 @@
+use crate::error::HistoryToDriveError;
+use calamine::{DataType, Reader, Xlsx};
+use std::path::Path;
+
+/// Which column holds each field, since not every export uses this crate's
+/// original six-column `Sheet1` layout.
+pub struct ColumnMapping {
+    pub timestamp: usize,
+    pub tags: usize,
+    pub title: usize,
+    pub host: usize,
+    pub url: usize,
+    pub user_agent: usize,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            timestamp: 0,
+            tags: 1,
+            title: 2,
+            host: 3,
+            url: 4,
+            user_agent: 5,
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Reads `HISTORY_TO_DRIVE_COLUMNS` as a comma-separated
+    /// `timestamp,tags,title,host,url,user_agent` list of column indices,
+    /// falling back to the original positional layout if it's unset.
+    pub fn from_env() -> Result<Self, HistoryToDriveError> {
+        let raw = match std::env::var("HISTORY_TO_DRIVE_COLUMNS") {
+            Ok(raw) => raw,
+            Err(_) => return Ok(ColumnMapping::default()),
+        };
+
+        let indices: Vec<usize> = raw
+            .split(',')
+            .map(|s| s.trim().parse::<usize>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| {
+                HistoryToDriveError::Unexpected(format!(
+                    "HISTORY_TO_DRIVE_COLUMNS must be 6 comma-separated column indices: {}",
+                    e
+                ))
+            })?;
+
+        if indices.len() != 6 {
+            return Err(HistoryToDriveError::Unexpected(format!(
+                "HISTORY_TO_DRIVE_COLUMNS must list exactly 6 columns \
+                    (timestamp,tags,title,host,url,user_agent), got {}",
+                indices.len()
+            )));
+        }
+
+        Ok(ColumnMapping {
+            timestamp: indices[0],
+            tags: indices[1],
+            title: indices[2],
+            host: indices[3],
+            url: indices[4],
+            user_agent: indices[5],
+        })
+    }
+
+    fn max_index(&self) -> usize {
+        [
+            self.timestamp,
+            self.tags,
+            self.title,
+            self.host,
+            self.url,
+            self.user_agent,
+        ]
+        .into_iter()
+        .max()
+        .unwrap()
+    }
+}
+
+/// Fetches a mapped column out of a row, or a clear error naming which
+/// field and column index was missing.
+pub fn column<'a>(
+    row: &'a [String],
+    index: usize,
+    field: &str,
+) -> Result<&'a str, HistoryToDriveError> {
+    row.get(index).map(|s| s.as_str()).ok_or_else(|| {
+        HistoryToDriveError::Unexpected(format!(
+            "row has no column {} for '{}' (row only has {} columns)",
+            index,
+            field,
+            row.len()
+        ))
+    })
+}
+
+/// Which shape a given input file is in. `worksheet` only applies to
+/// `Xlsx`.
+pub enum InputFormat {
+    Xlsx { worksheet: String },
+    Csv,
+    Tsv,
+}
+
+/// Picks a format from the file's extension: `.xlsx` is the original
+/// calamine workbook layout, `.csv`/`.tsv` are delimited text exports.
+pub fn detect_format(path: &Path) -> Result<InputFormat, HistoryToDriveError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("xlsx") => Ok(InputFormat::Xlsx {
+            worksheet: std::env::var("HISTORY_TO_DRIVE_WORKSHEET")
+                .unwrap_or_else(|_| "Sheet1".to_string()),
+        }),
+        Some("csv") => Ok(InputFormat::Csv),
+        Some("tsv") => Ok(InputFormat::Tsv),
+        _ => Err(HistoryToDriveError::Unexpected(format!(
+            "Unsupported file extension for {}",
+            path.display()
+        ))),
+    }
+}
+
+fn cell_to_string(cell: &DataType) -> Result<String, HistoryToDriveError> {
+    match cell {
+        DataType::String(s) => Ok(s.clone()),
+        DataType::Int(i) => Ok(format!("{}", i)),
+        DataType::Float(f) => Ok(format!("{}", f)),
+        DataType::Bool(b) => Ok(if *b {
+            "true".to_string()
+        } else {
+            "false".to_string()
+        }),
+        DataType::Error(cell_error_type) => Err(HistoryToDriveError::Unexpected(format!(
+            "Error: {}",
+            cell_error_type
+        ))),
+        DataType::Empty => Ok("".to_string()),
+    }
+}
+
+/// Reads every row of `path` under the given `format` into memory as plain
+/// strings, so the importer can walk rows the same way regardless of
+/// whether they came from a workbook or a delimited text file.
+pub fn read_rows(path: &str, format: &InputFormat) -> Result<Vec<Vec<String>>, HistoryToDriveError> {
+    match format {
+        InputFormat::Xlsx { worksheet } => {
+            let mut wb: Xlsx<_> = calamine::open_workbook(path)?;
+            let range = wb.worksheet_range(worksheet).ok_or_else(|| {
+                HistoryToDriveError::Unexpected(format!("Cannot find worksheet '{}'", worksheet))
+            })??;
+
+            range
+                .rows()
+                .map(|row| row.iter().map(cell_to_string).collect())
+                .collect()
+        }
+        InputFormat::Csv | InputFormat::Tsv => {
+            let delimiter = if matches!(format, InputFormat::Tsv) {
+                b'\t'
+            } else {
+                b','
+            };
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .has_headers(false)
+                .from_path(path)
+                .map_err(|e| {
+                    HistoryToDriveError::Unexpected(format!("could not read {}: {}", path, e))
+                })?;
+
+            reader
+                .records()
+                .map(|record| {
+                    record
+                        .map(|r| r.iter().map(|field| field.to_string()).collect())
+                        .map_err(|e| {
+                            HistoryToDriveError::Unexpected(format!("could not parse row: {}", e))
+                        })
+                })
+                .collect()
+        }
+    }
+}
+
+/// Validates that every mapped column index is within the columns present
+/// on at least the first row, so a misconfigured mapping fails loudly
+/// before spending time on the import instead of row-by-row.
+pub fn ensure_mapping_fits(mapping: &ColumnMapping, rows: &[Vec<String>]) -> Result<(), HistoryToDriveError> {
+    if let Some(first) = rows.first() {
+        if first.len() <= mapping.max_index() {
+            return Err(HistoryToDriveError::Unexpected(format!(
+                "column mapping expects at least {} columns, but rows only have {}",
+                mapping.max_index() + 1,
+                first.len()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // HISTORY_TO_DRIVE_COLUMNS is process-global, so serialize tests that set it.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn from_env_falls_back_to_default_layout_when_unset() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        std::env::remove_var("HISTORY_TO_DRIVE_COLUMNS");
+        let mapping = ColumnMapping::from_env().unwrap();
+        assert_eq!(mapping.timestamp, 0);
+        assert_eq!(mapping.user_agent, 5);
+    }
+
+    #[test]
+    fn from_env_parses_a_custom_layout() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        std::env::set_var("HISTORY_TO_DRIVE_COLUMNS", "5,4,3,2,1,0");
+        let mapping = ColumnMapping::from_env().unwrap();
+        std::env::remove_var("HISTORY_TO_DRIVE_COLUMNS");
+
+        assert_eq!(mapping.timestamp, 5);
+        assert_eq!(mapping.tags, 4);
+        assert_eq!(mapping.title, 3);
+        assert_eq!(mapping.host, 2);
+        assert_eq!(mapping.url, 1);
+        assert_eq!(mapping.user_agent, 0);
+    }
+
+    #[test]
+    fn from_env_rejects_the_wrong_number_of_columns() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        std::env::set_var("HISTORY_TO_DRIVE_COLUMNS", "0,1,2");
+        let result = ColumnMapping::from_env();
+        std::env::remove_var("HISTORY_TO_DRIVE_COLUMNS");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn from_env_rejects_non_numeric_columns() {
+        let _guard = ENV_LOCK.lock().unwrap();
+        std::env::set_var("HISTORY_TO_DRIVE_COLUMNS", "a,b,c,d,e,f");
+        let result = ColumnMapping::from_env();
+        std::env::remove_var("HISTORY_TO_DRIVE_COLUMNS");
+
+        assert!(result.is_err());
+    }
+}
@@ -1,262 +1,182 @@
-use calamine::{open_workbook, DataType, Reader, Xlsx};
+mod encryption;
+mod error;
+mod format;
+mod migrations;
+mod search;
+mod store;
+
 use chrono::prelude::*;
-use postgres::{Client, NoTls};
-use std::fmt;
+use encryption::HistoryCipher;
+use error::HistoryToDriveError;
+use format::{ColumnMapping, InputFormat};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::path::Path;
+use store::{HistoryRow, HistoryStore};
+
+/// Read every row of `path` under `input_format`, and use the given store to
+/// insert history rows, insert tag rows, insert link from history to tags
+async fn insert_sheet(
+    path: &str,
+    origin: &str,
+    input_format: &InputFormat,
+    mapping: &ColumnMapping,
+    store: &mut dyn HistoryStore,
+    cipher: Option<&HistoryCipher>,
+) -> Result<i32, HistoryToDriveError> {
+    let rows = format::read_rows(path, input_format)?;
+    format::ensure_mapping_fits(mapping, &rows)?;
 
-/// SQL to create the schema with tables to hold data for History to Drive
-const CREATE_SCHEMA_SQL: &str = "\
-    create schema if not exists history_to_drive;
-
-    create table if not exists history_to_drive.history
-    (
-        history_id         serial        not null
-            constraint history_pk primary key,
-        timestamp          timestamp     not null,
-        title              varchar(1000) not null,
-        host               varchar(600)  not null,
-        url                varchar(3000) not null,
-        user_agent         varchar(3000) not null,
-        origin_description varchar(100)  not null,
-        origin_timestamp   timestamp     not null default now()
-    );
-
-    comment on table history_to_drive.history is 'Browser history from History To Drive';
-    comment on column history_to_drive.history.timestamp is 'UTC datetime when the page was visited';
-    comment on column history_to_drive.history.title is 'The document title of the page';
-    comment on column history_to_drive.history.host is 'The window.location.host of the page';
-    comment on column history_to_drive.history.url is 'The window.location.href of the page';
-    comment on column history_to_drive.history.origin_description is 'Source file or author for the record';
-    comment on column history_to_drive.history.origin_timestamp is 'UTC datetime when the record was inserted from the origin';
-
-    create index if not exists history_to_drive_history_ix_origin_ts
-        on history_to_drive.history (origin_description, timestamp);
-    create index if not exists history_to_drive_history_ix_host_ts
-        on history_to_drive.history (host, timestamp);
-    create index if not exists history_to_drive_history_ix_ts
-        on history_to_drive.history (timestamp);
-
-    create table if not exists history_to_drive.tag
-    (
-        tag_id serial       not null
-            constraint tags_pk
-                primary key,
-        tag    varchar(100) not null
-    );
-
-    comment on table history_to_drive.tag is 'Tags linked to browser history';
-    create unique index if not exists history_to_drive_tags_tag_uindex
-        on history_to_drive.tag (tag);
-
-    create table if not exists history_to_drive.history_tag
-    (
-        history_id int not null
-            constraint history_tag_history_id_fkey
-                references history_to_drive.history,
-        tag_id     int not null
-            constraint history_tag_tag_id_fkey
-                references history_to_drive.tag
-    );
-
-    comment on table history_to_drive.history_tag is 'Table to join tags to history';
-    create unique index if not exists history_to_drive_history_tag_uindex
-        on history_to_drive.history_tag (history_id, tag_id);";
-
-/// SQL to insert history rows, insert tag rows, insert link from history to tags
-const INSERT_HISTORY_ROW_SQL: &str = "\
-    with record_insert_id as (
-        insert into history_to_drive.history (timestamp, title, host, url, user_agent, origin_description)
-            values ($1, $2, $3, $4, $5, $6)
-            returning history_id
-    )
-       , tags_to_merge as (
-        select tag
-        from unnest($7::varchar[]) as t(tag)
-    )
-       , inserted_tags as (
-        insert into history_to_drive.tag (tag)
-            select tag
-            from tags_to_merge
-            where tag not in (select tag from history_to_drive.tag)
-            returning tag_id
-    )
-       , tag_ids as (
-        select tag_id
-        from inserted_tags
-        union
-        select tag_id
-        from history_to_drive.tag
-        where tag in (select tag from tags_to_merge)
-    )
-    insert
-    into history_to_drive.history_tag (history_id, tag_id)
-    select r.history_id, t.tag_id
-    from tag_ids t
-        cross join record_insert_id r";
-
-enum HistoryToDriveError {
-    DeserializeError(calamine::DeError),
-    ExcelError(calamine::XlsxError),
-    CalamineError(calamine::Error),
-    PostgresError(postgres::Error),
-    Unexpected(String),
-}
-
-impl From<calamine::DeError> for HistoryToDriveError {
-    fn from(error: calamine::DeError) -> Self {
-        HistoryToDriveError::DeserializeError(error)
-    }
-}
-
-impl From<calamine::XlsxError> for HistoryToDriveError {
-    fn from(error: calamine::XlsxError) -> Self {
-        HistoryToDriveError::ExcelError(error)
-    }
-}
-
-impl From<calamine::Error> for HistoryToDriveError {
-    fn from(error: calamine::Error) -> Self {
-        HistoryToDriveError::CalamineError(error)
-    }
-}
-
-impl From<postgres::Error> for HistoryToDriveError {
-    fn from(error: postgres::Error) -> Self {
-        HistoryToDriveError::PostgresError(error)
-    }
-}
-
-impl fmt::Debug for HistoryToDriveError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            HistoryToDriveError::DeserializeError(de) => de.fmt(f),
-            HistoryToDriveError::ExcelError(xlsx) => xlsx.fmt(f),
-            HistoryToDriveError::CalamineError(cal) => cal.fmt(f),
-            HistoryToDriveError::PostgresError(post) => post.fmt(f),
-            HistoryToDriveError::Unexpected(s) => s.fmt(f),
-        }
-    }
-}
-
-fn create_schema(url: &str) -> Result<(), HistoryToDriveError> {
-    let mut client = Client::connect(&url, NoTls)?;
-    return client
-        .batch_execute(CREATE_SCHEMA_SQL)
-        .map_err(|e| HistoryToDriveError::from(e));
-}
-
-fn get_string(cell: &DataType) -> Result<String, HistoryToDriveError> {
-    match cell {
-        DataType::String(s) => Ok(s.clone()),
-        DataType::Int(i) => Ok(format!("{}", i)),
-        DataType::Float(f) => Ok(format!("{}", f)),
-        DataType::Bool(b) => Ok(if *b {
-            "true".to_string()
-        } else {
-            "false".to_string()
-        }),
-        DataType::Error(cell_error_type) => Err(HistoryToDriveError::Unexpected(format!(
-            "Error: {}",
-            cell_error_type
-        ))),
-        DataType::Empty => Ok("".to_string()),
-    }
-}
-
-/// Read the Excel worksheet at the given path, and execute SQL with the given PostgreSQL URL
-/// to insert history rows, insert tag rows, insert link from history to tags
-fn insert_sheet(path: &String, origin: &str, url: &str) -> Result<i32, HistoryToDriveError> {
-    let mut wb: Xlsx<_> = open_workbook(path)?;
-    let range = wb
-        .worksheet_range("Sheet1")
-        .ok_or(calamine::Error::Msg("Cannot find 'Sheet1'"))??;
-
-    let mut rows = range.rows().into_iter();
-    let mut client = Client::connect(&url, NoTls)?;
     let mut p_origin = origin.to_string();
     p_origin.truncate(100);
 
-    let last_ts = client
-        .query_one(
-            "\
-            select coalesce(max(h.timestamp), '1970-01-01') last_ts
-            from history_to_drive.history h
-            where h.origin_description = $1",
-            &[&p_origin],
+    let progress = ProgressBar::new(rows.len() as u64);
+    if !atty::is(atty::Stream::Stdout) {
+        progress.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    progress.set_style(
+        ProgressStyle::with_template(
+            "{prefix}: {elapsed_precise} [{bar:40.cyan/blue}] {pos}/{len} rows ({msg})",
         )
-        .map_or(chrono::naive::MIN_DATETIME, |r| r.get("last_ts"));
+        .unwrap()
+        .progress_chars("=> "),
+    );
+    progress.set_prefix(p_origin.clone());
+    progress.set_message("0 skipped");
+
+    let last_ts = store.last_timestamp(&p_origin).await?;
     if last_ts.year() > 1970 {
-        println!(
+        progress.println(format!(
             "Max timestamp of {} found for {}, skipping records before then",
             last_ts, p_origin
-        );
+        ));
     } else {
-        println!("No previous records found for {}", p_origin);
+        progress.println(format!("No previous records found for {}", p_origin));
     }
 
-    let mut count = 0;
-
-    'runner: loop {
-        let mut txn = client.transaction()?;
-        let sql = txn.prepare(INSERT_HISTORY_ROW_SQL)?;
+    // When encryption is on, fields are stored as base64(nonce || ciphertext),
+    // so the plaintext has to be truncated tighter than the column limit to
+    // leave room for that expansion.
+    let limits = store.column_limits();
+    let (title_limit, host_limit, url_limit, ua_limit) = if cipher.is_some() {
+        (
+            limits.title.map(HistoryCipher::max_plaintext_len),
+            limits.host.map(HistoryCipher::max_plaintext_len),
+            limits.url.map(HistoryCipher::max_plaintext_len),
+            limits.user_agent,
+        )
+    } else {
+        (limits.title, limits.host, limits.url, limits.user_agent)
+    };
 
-        while let Some(row) = rows.next() {
-            // print!("{}, ", count);
-            if &row.len() < &6 {
-                return Err(HistoryToDriveError::Unexpected(format!(
-                    "Only {} columns present",
-                    &row.len()
-                )));
+    let mut count = 0;
+    let mut skipped = 0u64;
+    let mut batch: Vec<HistoryRow> = Vec::with_capacity(1000);
+
+    for row in &rows {
+        progress.inc(1);
+
+        let ts = format::column(row, mapping.timestamp, "timestamp")?;
+        let tags = format::column(row, mapping.tags, "tags")?;
+        // ignore #NAME errors that are possible for title
+        let mut title = format::column(row, mapping.title, "title")
+            .unwrap_or("")
+            .to_string();
+        let mut host = format::column(row, mapping.host, "host")?.to_string();
+        let mut url = format::column(row, mapping.url, "url")?.to_string();
+        let mut ua = format::column(row, mapping.user_agent, "user_agent")?.to_string();
+
+        if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(ts) {
+            // Don't insert duplicate records, assumes timestamps from an origin only increase
+            let pts: NaiveDateTime = timestamp.naive_utc();
+            if pts <= last_ts {
+                skipped += 1;
+                progress.set_message(format!("{} skipped", skipped));
+                continue;
             }
 
-            let ts = get_string(&row[0])?;
-            let tags = get_string(&row[1])?;
-            // ignore #NAME errors that are possible for title
-            let mut title = get_string(&row[2]).unwrap_or("".to_string());
-            let mut host = get_string(&row[3])?;
-            let mut url = get_string(&row[4])?;
-            let mut ua = get_string(&row[5])?;
-
-            if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(&ts) {
-                // Don't insert duplicate records, assumes timestamps from an origin only increase
-                let pts: NaiveDateTime = timestamp.naive_utc();
-                if pts <= last_ts {
-                    continue;
-                }
-
-                title.truncate(1000);
-                host.truncate(600);
-                url.truncate(3000);
-                ua.truncate(3000);
-                let tags = tags
-                    .split(";")
-                    .filter(|t| t.chars().count() < 100)
-                    .map(|t| t.trim())
-                    .collect();
-                let ptags = postgres_array::Array::from_vec(tags, 0);
-                txn.execute(&sql, &[&pts, &title, &host, &url, &ua, &p_origin, &ptags])?;
+            if let Some(limit) = title_limit {
+                title.truncate(limit);
+            }
+            if let Some(limit) = host_limit {
+                host.truncate(limit);
+            }
+            if let Some(limit) = url_limit {
+                url.truncate(limit);
+            }
+            if let Some(limit) = ua_limit {
+                ua.truncate(limit);
+            }
+            let tags = tags
+                .split(";")
+                .filter(|t| t.chars().count() < 100)
+                .map(|t| t.trim().to_string())
+                .collect();
+
+            if let Some(cipher) = cipher {
+                title = cipher.encrypt_field(&title)?;
+                host = cipher.encrypt_field(&host)?;
+                url = cipher.encrypt_field(&url)?;
+            }
 
-                count += 1;
-                if count % 1000 == 0 {
-                    txn.commit()?;
-                    continue 'runner;
-                }
+            batch.push(HistoryRow {
+                timestamp: pts,
+                title,
+                host,
+                url,
+                user_agent: ua,
+                origin_description: p_origin.clone(),
+                tags,
+            });
+
+            count += 1;
+            if batch.len() >= 1000 {
+                store.insert_batch(&batch).await?;
+                batch.clear();
             }
         }
+    }
 
-        txn.commit()?;
-        break;
+    if !batch.is_empty() {
+        store.insert_batch(&batch).await?;
     }
 
+    progress.finish_with_message(format!("{} inserted, {} skipped", count, skipped));
+
     Ok(count)
 }
 
 /// Call with one or more file paths and POSTGRESQL_URL as an environment variable.
 /// For instance: `find "$(pwd)" -name "*.xlsx" | xargs -d '\n' historytodrivedb`
-fn main() {
-    let args: Vec<String> = std::env::args().skip(1).collect();
+/// `POSTGRESQL_URL` may also be a `sqlite://path/to/file.db` URL to import into
+/// a local, self-contained SQLite database instead of Postgres. Files are
+/// imported as `.xlsx`, `.csv`, or `.tsv` based on their extension;
+/// `HISTORY_TO_DRIVE_WORKSHEET` overrides the worksheet name for `.xlsx`
+/// files (default `Sheet1`), and `HISTORY_TO_DRIVE_COLUMNS` overrides which
+/// column holds which field as a comma-separated
+/// `timestamp,tags,title,host,url,user_agent` list of indices.
+/// Pass `--encrypt` (or set `HISTORY_TO_DRIVE_ENCRYPT=1`) to encrypt the
+/// `title`, `url`, and `host` columns before they're inserted, using a key
+/// stored at `HISTORY_TO_DRIVE_KEY_FILE` (default `history_to_drive.key`).
+/// Call with `search <query> [--host ...] [--tag ...] [--before YYYY-MM-DD]
+/// [--after YYYY-MM-DD] [--limit N]` to query previously imported history.
+/// Pass `--encrypt` (or set `HISTORY_TO_DRIVE_ENCRYPT=1`) here too if the
+/// history was imported with encryption on, so `search` can decrypt
+/// `title`/`host`/`url` with the same `HISTORY_TO_DRIVE_KEY_FILE` before
+/// matching and displaying them.
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|a| a.as_str()) == Some("search") {
+        search::run(&args[1..]).await;
+        return;
+    }
+
+    let encrypt = encryption::requested(&args);
+    args.retain(|a| a != "--encrypt");
+
     if args.len() < 1 {
-        println!("Not enough arguments - expecting one or more paths to an Excel file");
+        println!("Not enough arguments - expecting one or more paths to an Excel, CSV, or TSV file");
         return;
     }
 
@@ -267,21 +187,64 @@ fn main() {
         }
     }
 
+    let mapping = match ColumnMapping::from_env() {
+        Ok(mapping) => mapping,
+        Err(e) => {
+            println!("Invalid column mapping: {:?}", e);
+            return;
+        }
+    };
+
+    let cipher = if encrypt {
+        let key_file = encryption::key_file_path();
+        match HistoryCipher::load_or_create(Path::new(&key_file)) {
+            Ok(cipher) => Some(cipher),
+            Err(e) => {
+                println!("Could not load encryption key: {:?}", e);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
     let pg_url = std::env::var("POSTGRESQL_URL");
     match pg_url {
-        Ok(pg) => match create_schema(&pg) {
-            Ok(_) => {
-                for path in &args {
-                    if let Some(file_name) = Path::new(path).file_name().and_then(|n| n.to_str()) {
-                        println!("Importing {} ...", &file_name);
-                        match insert_sheet(path, &file_name, &pg) {
-                            Ok(count) => println!("Done inserting {} history rows", count),
-                            Err(v) => println!("{:?}", v),
+        Ok(pg) => match store::connect(&pg).await {
+            Ok(mut store) => match store.ensure_schema().await {
+                Ok(_) => {
+                    for path in &args {
+                        let input_format = match format::detect_format(Path::new(path)) {
+                            Ok(input_format) => input_format,
+                            Err(e) => {
+                                println!("{:?}", e);
+                                continue;
+                            }
+                        };
+
+                        if let Some(file_name) =
+                            Path::new(path).file_name().and_then(|n| n.to_str())
+                        {
+                            println!("Importing {} ...", &file_name);
+                            match insert_sheet(
+                                path,
+                                file_name,
+                                &input_format,
+                                &mapping,
+                                store.as_mut(),
+                                cipher.as_ref(),
+                            )
+                            .await
+                            {
+                                Ok(count) => println!("Done inserting {} history rows", count),
+                                Err(v) => println!("{:?}", v),
+                            }
                         }
                     }
                 }
-            }
-            Err(e) => println!("Could not create schema {:?}", e),
+                Err(e) => println!("Could not create schema {:?}", e),
+            },
+            Err(e) => println!("Could not connect to store {:?}", e),
         },
         Err(_) => println!("Could not find environment variable 'POSTGRESQL_URL'"),
     }
@@ -0,0 +1,78 @@
+pub mod postgres;
+pub mod sqlite;
+
+/// A single embedded schema migration. `version` must be strictly
+/// increasing within a backend's migration list; `sql` is applied verbatim
+/// in one transaction the first time a database reaches that version.
+///
+/// Each backend's `run_migrations` bootstraps a `schema_migrations` table
+/// (the one piece of schema that can never itself be a numbered migration,
+/// since the runner needs it to know which migrations have already run),
+/// then applies, in version order and each in its own transaction, every
+/// migration from that backend's `MIGRATIONS` list whose version is above
+/// the highest one recorded there.
+/// It fails loudly if a migration that's already applied no longer matches
+/// the checksum embedded in the binary, since that means the migration
+/// history has diverged from the code that's running.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// A fingerprint of this migration's SQL, used to detect drift between
+    /// what's recorded as applied and what's actually embedded in the
+    /// binary. Uses FNV-1a rather than `DefaultHasher`, since the latter's
+    /// algorithm is explicitly unspecified and can change between Rust
+    /// releases, which would turn an unrelated toolchain bump into a
+    /// false-positive checksum mismatch for every existing install.
+    pub fn checksum(&self) -> i64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+
+        let mut hash = FNV_OFFSET_BASIS;
+        for byte in self.sql.as_bytes() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        hash as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_is_stable_for_the_same_sql() {
+        let a = Migration { version: 1, name: "a", sql: "select 1;" };
+        let b = Migration { version: 1, name: "a", sql: "select 1;" };
+        assert_eq!(a.checksum(), b.checksum());
+    }
+
+    #[test]
+    fn checksum_differs_when_sql_differs() {
+        let a = Migration { version: 1, name: "a", sql: "select 1;" };
+        let b = Migration { version: 1, name: "a", sql: "select 2;" };
+        assert_ne!(a.checksum(), b.checksum());
+    }
+
+    #[test]
+    fn postgres_migrations_have_strictly_increasing_versions() {
+        let versions: Vec<i32> = postgres::MIGRATIONS.iter().map(|m| m.version).collect();
+        let mut sorted = versions.clone();
+        sorted.sort();
+        assert_eq!(versions, sorted);
+        assert!(versions.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn sqlite_migrations_have_strictly_increasing_versions() {
+        let versions: Vec<i32> = sqlite::MIGRATIONS.iter().map(|m| m.version).collect();
+        let mut sorted = versions.clone();
+        sorted.sort();
+        assert_eq!(versions, sorted);
+        assert!(versions.windows(2).all(|w| w[0] != w[1]));
+    }
+}
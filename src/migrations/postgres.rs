@@ -0,0 +1,12 @@
+use super::Migration;
+
+/// Embedded, ordered Postgres migrations. Add new entries here (and a new
+/// numbered `.sql` file alongside this module) rather than editing an
+/// existing migration once it has shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("postgres/0001_initial.sql"),
+    },
+];
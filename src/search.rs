@@ -0,0 +1,210 @@
+use crate::encryption::{self, HistoryCipher};
+use crate::error::HistoryToDriveError;
+use crate::store::{self, HistoryRecord, SearchFilters};
+use chrono::NaiveDate;
+use std::path::Path;
+
+const DEFAULT_LIMIT: i64 = 50;
+
+/// When encryption is on, `title`/`host`/`url` are ciphertext in the store,
+/// so the `query`/`--host` filters can't be pushed into SQL - they're
+/// applied after decrypting. Over-fetch by this factor before filtering, so
+/// a `--limit` of N still has a fair chance of finding N matches.
+const ENCRYPTED_OVERFETCH_FACTOR: i64 = 20;
+
+struct SearchArgs {
+    query: String,
+    host: Option<String>,
+    tag: Option<String>,
+    before: Option<chrono::NaiveDateTime>,
+    after: Option<chrono::NaiveDateTime>,
+    limit: i64,
+}
+
+/// Parses `search` subcommand arguments: any arg not recognized as one of
+/// `--host`/`--tag`/`--before`/`--after`/`--limit` is treated as part of the
+/// free-text query and joined with spaces.
+fn parse_args(args: &[String]) -> SearchArgs {
+    let mut query_words = Vec::new();
+    let mut host = None;
+    let mut tag = None;
+    let mut before = None;
+    let mut after = None;
+    let mut limit = DEFAULT_LIMIT;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--host" => host = iter.next().cloned(),
+            "--tag" => tag = iter.next().cloned(),
+            "--before" => before = iter.next().and_then(|s| parse_before_date(s)),
+            "--after" => after = iter.next().and_then(|s| parse_date(s)),
+            "--limit" => limit = iter.next().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_LIMIT),
+            other => query_words.push(other),
+        }
+    }
+
+    SearchArgs {
+        query: query_words.join(" "),
+        host,
+        tag,
+        before,
+        after,
+        limit,
+    }
+}
+
+fn parse_date(s: &str) -> Option<chrono::NaiveDateTime> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+}
+
+/// Parses `--before`'s `YYYY-MM-DD` as the start of the *next* day, so it's
+/// an exclusive upper bound that still includes every row on the given day
+/// (matching `--after`'s inclusive, start-of-day lower bound).
+fn parse_before_date(s: &str) -> Option<chrono::NaiveDateTime> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.succ_opt())
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+}
+
+/// Runs the `search` subcommand: query imported history from the CLI
+/// without having to write SQL by hand.
+pub async fn run(args: &[String]) {
+    let encrypted = encryption::requested(args);
+    let args: Vec<String> = args.iter().filter(|a| *a != "--encrypt").cloned().collect();
+    let parsed = parse_args(&args);
+
+    let cipher = if encrypted {
+        let key_file = encryption::key_file_path();
+        match HistoryCipher::load_or_create(Path::new(&key_file)) {
+            Ok(cipher) => Some(cipher),
+            Err(e) => {
+                println!("Could not load encryption key: {:?}", e);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    let pg_url = match std::env::var("POSTGRESQL_URL") {
+        Ok(pg) => pg,
+        Err(_) => {
+            println!("Could not find environment variable 'POSTGRESQL_URL'");
+            return;
+        }
+    };
+
+    let mut store = match store::connect(&pg_url).await {
+        Ok(store) => store,
+        Err(e) => {
+            println!("Could not connect to store {:?}", e);
+            return;
+        }
+    };
+
+    // With encryption on, title/host/url are ciphertext, so the query and
+    // host filters can't be evaluated by the store - fetch a wider,
+    // unfiltered-on-those-fields candidate set and filter after decrypting.
+    let filters = if cipher.is_some() {
+        SearchFilters {
+            query: "",
+            host: None,
+            tag: parsed.tag.as_deref(),
+            before: parsed.before,
+            after: parsed.after,
+            limit: parsed.limit * ENCRYPTED_OVERFETCH_FACTOR,
+        }
+    } else {
+        SearchFilters {
+            query: &parsed.query,
+            host: parsed.host.as_deref(),
+            tag: parsed.tag.as_deref(),
+            before: parsed.before,
+            after: parsed.after,
+            limit: parsed.limit,
+        }
+    };
+
+    match store.search(&filters).await {
+        Ok(records) => match cipher {
+            Some(cipher) => match decrypt_and_filter(&cipher, records, &parsed) {
+                Ok(records) => print_results(&records),
+                Err(e) => println!("Could not decrypt results: {:?}", e),
+            },
+            None => print_results(&records),
+        },
+        Err(e) => println!("Search failed {:?}", e),
+    }
+}
+
+/// Decrypts `title`/`host`/`url` on each record, then applies the
+/// `--host`/free-text filters client-side (mirroring `SEARCH_SQL`'s
+/// `h.host = $2` and case-insensitive substring-of-title-or-url match)
+/// since the store could only filter on the still-encrypted bytes, and
+/// truncates to the user's requested `--limit`.
+fn decrypt_and_filter(
+    cipher: &HistoryCipher,
+    records: Vec<HistoryRecord>,
+    parsed: &SearchArgs,
+) -> Result<Vec<HistoryRecord>, HistoryToDriveError> {
+    let query = parsed.query.to_lowercase();
+    let mut decrypted = Vec::with_capacity(records.len());
+    for record in records {
+        let title = cipher.decrypt_field(&record.title)?;
+        let host = cipher.decrypt_field(&record.host)?;
+        let url = cipher.decrypt_field(&record.url)?;
+
+        if let Some(filter_host) = &parsed.host {
+            if &host != filter_host {
+                continue;
+            }
+        }
+        if !query.is_empty()
+            && !title.to_lowercase().contains(&query)
+            && !url.to_lowercase().contains(&query)
+        {
+            continue;
+        }
+
+        decrypted.push(HistoryRecord {
+            timestamp: record.timestamp,
+            title,
+            host,
+            url,
+            tags: record.tags,
+        });
+        if decrypted.len() as i64 >= parsed.limit {
+            break;
+        }
+    }
+    Ok(decrypted)
+}
+
+fn print_results(records: &[HistoryRecord]) {
+    if records.is_empty() {
+        println!("No matching history found");
+        return;
+    }
+
+    println!(
+        "{:<19}  {:<30}  {:<50}  {}",
+        "timestamp", "host", "title", "tags"
+    );
+    for record in records {
+        println!(
+            "{:<19}  {:<30}  {:<50}  {}",
+            record.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            truncate_chars(&record.host, 30),
+            truncate_chars(&record.title, 50),
+            record.tags.join(",")
+        );
+    }
+}
+
+fn truncate_chars(s: &str, limit: usize) -> String {
+    s.chars().take(limit).collect()
+}
@@ -0,0 +1,97 @@
+mod postgres;
+mod sqlite;
+
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+use crate::error::HistoryToDriveError;
+use async_trait::async_trait;
+use chrono::naive::NaiveDateTime;
+
+/// A single browser history record ready to be persisted, after column
+/// extraction and truncation has already happened.
+pub struct HistoryRow {
+    pub timestamp: NaiveDateTime,
+    pub title: String,
+    pub host: String,
+    pub url: String,
+    pub user_agent: String,
+    pub origin_description: String,
+    pub tags: Vec<String>,
+}
+
+/// A history row read back out of the store, for the `search` subcommand.
+pub struct HistoryRecord {
+    pub timestamp: NaiveDateTime,
+    pub title: String,
+    pub host: String,
+    pub url: String,
+    pub tags: Vec<String>,
+}
+
+/// Maximum byte length a backend's schema allows for each of
+/// `title`/`host`/`url`/`user_agent` before insertion, so callers can
+/// truncate to fit without knowing the schema themselves. `None` means the
+/// backend doesn't bound that field (e.g. SQLite's `text` columns).
+pub struct ColumnLimits {
+    pub title: Option<usize>,
+    pub host: Option<usize>,
+    pub url: Option<usize>,
+    pub user_agent: Option<usize>,
+}
+
+/// Filters for `HistoryStore::search`. `query` is matched as a
+/// case-insensitive substring of `title` or `url`; everything else narrows
+/// the result set further when present. `before` is an exclusive upper
+/// bound (`timestamp < before`) and `after` an inclusive lower bound
+/// (`timestamp >= after`), so a caller wanting "on or before day D" should
+/// pass the start of the day after D.
+pub struct SearchFilters<'a> {
+    pub query: &'a str,
+    pub host: Option<&'a str>,
+    pub tag: Option<&'a str>,
+    pub before: Option<NaiveDateTime>,
+    pub after: Option<NaiveDateTime>,
+    pub limit: i64,
+}
+
+/// Backend-agnostic persistence for imported history rows. Implementations
+/// own their own connection/pool and are responsible for deduping tags and
+/// linking them to `history` rows.
+#[async_trait]
+pub trait HistoryStore: Send {
+    /// Create the `history_to_drive` schema (tables, indexes) if it does not
+    /// already exist.
+    async fn ensure_schema(&mut self) -> Result<(), HistoryToDriveError>;
+
+    /// The per-field length limits this backend's schema imposes, so the
+    /// caller can truncate to fit before building a `HistoryRow`.
+    fn column_limits(&self) -> ColumnLimits;
+
+    /// The latest `timestamp` already recorded for the given origin, or a
+    /// timestamp no later than 1970-01-01 if nothing has been imported for it
+    /// yet (`1970-01-01` from Postgres, `chrono::naive::MIN_DATETIME` from
+    /// SQLite).
+    async fn last_timestamp(&mut self, origin: &str) -> Result<NaiveDateTime, HistoryToDriveError>;
+
+    /// Insert a batch of rows, merging tags and their links alongside the
+    /// history rows.
+    async fn insert_batch(&mut self, rows: &[HistoryRow]) -> Result<(), HistoryToDriveError>;
+
+    /// Find history rows matching `filters`, most recent first.
+    async fn search(
+        &mut self,
+        filters: &SearchFilters,
+    ) -> Result<Vec<HistoryRecord>, HistoryToDriveError>;
+}
+
+/// Pick a `HistoryStore` implementation based on the URL scheme: `sqlite://`
+/// opens (and creates, if missing) a local file, anything else is handed to
+/// the Postgres pool as-is.
+pub async fn connect(url: &str) -> Result<Box<dyn HistoryStore>, HistoryToDriveError> {
+    if let Some(path) = url.strip_prefix("sqlite://") {
+        Ok(Box::new(SqliteStore::connect(path)?))
+    } else {
+        Ok(Box::new(PostgresStore::connect(url).await?))
+    }
+}
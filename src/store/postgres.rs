@@ -0,0 +1,294 @@
+use super::{ColumnLimits, HistoryRecord, HistoryRow, HistoryStore, SearchFilters};
+use crate::error::HistoryToDriveError;
+use crate::migrations;
+use async_trait::async_trait;
+use chrono::naive::NaiveDateTime;
+use sqlx::postgres::{PgPoolOptions, PgRow};
+use sqlx::{Acquire, Executor, Row};
+use std::collections::HashMap;
+
+/// Bootstraps the table `run_migrations` depends on; see the `Migration` doc
+/// for why this can't itself be a numbered migration.
+const CREATE_MIGRATIONS_TABLE_SQL: &str = "\
+    create schema if not exists history_to_drive;
+
+    create table if not exists history_to_drive.schema_migrations
+    (
+        version    int       not null primary key,
+        name       varchar(200) not null,
+        checksum   bigint    not null,
+        applied_at timestamp not null default now()
+    );";
+
+/// SQL to insert every row already `COPY`'d into the staging table into
+/// `history`, unconditionally (the caller is responsible for dedup, via
+/// `last_timestamp`, before building the batch), returning each new row's
+/// id alongside the `staging_seq` it came from so the caller can map tags
+/// back to the right history row without relying on `(origin_description,
+/// timestamp)` being unique - two genuinely distinct rows can share a
+/// timestamp within the same batch (e.g. two visits rounding to the same
+/// second), and merging those into one row would silently drop data.
+const INSERT_STAGED_HISTORY_SQL: &str = "\
+    with staged as (
+        select nextval(pg_get_serial_sequence('history_to_drive.history', 'history_id'))::int
+                   as history_id, s.*
+        from history_staging s
+    ), inserted as (
+    insert into history_to_drive.history
+        (history_id, timestamp, title, host, url, user_agent, origin_description)
+        select history_id, timestamp, title, host, url, user_agent, origin_description
+        from staged
+    )
+    select history_id, staging_seq from staged";
+
+/// SQL to insert tag rows and the link from a history row to its tags.
+const MERGE_TAGS_SQL: &str = "\
+    with tags_to_merge as (
+        select tag
+        from unnest($1::varchar[]) as t(tag)
+    )
+       , inserted_tags as (
+        insert into history_to_drive.tag (tag)
+            select tag
+            from tags_to_merge
+            where tag not in (select tag from history_to_drive.tag)
+            returning tag_id
+    )
+       , tag_ids as (
+        select tag_id
+        from inserted_tags
+        union
+        select tag_id
+        from history_to_drive.tag
+        where tag in (select tag from tags_to_merge)
+    )
+    insert into history_to_drive.history_tag (history_id, tag_id)
+    select $2, tag_id
+    from tag_ids
+    on conflict do nothing";
+
+/// SQL behind `search`: a substring match against `title`/`url`, narrowed by
+/// whichever optional filters are present, most recent first.
+const SEARCH_SQL: &str = "\
+    select h.timestamp, h.title, h.host, h.url,
+           coalesce(array_agg(t.tag) filter (where t.tag is not null), '{}') as tags
+    from history_to_drive.history h
+        left join history_to_drive.history_tag ht on ht.history_id = h.history_id
+        left join history_to_drive.tag t on t.tag_id = ht.tag_id
+    where (h.title ilike $1 or h.url ilike $1)
+      and ($2::varchar is null or h.host = $2)
+      and ($3::varchar is null
+           or exists (
+               select 1
+               from history_to_drive.history_tag ht2
+                   join history_to_drive.tag t2 on t2.tag_id = ht2.tag_id
+               where ht2.history_id = h.history_id and t2.tag = $3
+           ))
+      and ($4::timestamp is null or h.timestamp >= $4)
+      and ($5::timestamp is null or h.timestamp < $5)
+    group by h.history_id
+    order by h.timestamp desc
+    limit $6";
+
+/// Postgres-backed `HistoryStore`. Bulk inserts go through a `COPY` into a
+/// per-transaction staging table followed by a single merge, rather than one
+/// round-trip per row.
+pub struct PostgresStore {
+    pool: sqlx::PgPool,
+}
+
+impl PostgresStore {
+    pub async fn connect(url: &str) -> Result<Self, HistoryToDriveError> {
+        let pool = PgPoolOptions::new().max_connections(8).connect(url).await?;
+        Ok(PostgresStore { pool })
+    }
+
+    /// Applies `migrations::postgres::MIGRATIONS` against `self.pool`; see
+    /// the `Migration` doc for the general behavior.
+    async fn run_migrations(&mut self) -> Result<(), HistoryToDriveError> {
+        self.pool.execute(CREATE_MIGRATIONS_TABLE_SQL).await?;
+
+        let applied: Vec<PgRow> = sqlx::query(
+            "select version, checksum from history_to_drive.schema_migrations order by version",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut current_version = 0;
+        for row in &applied {
+            let version: i32 = row.get("version");
+            let checksum: i64 = row.get("checksum");
+            if let Some(migration) = migrations::postgres::MIGRATIONS
+                .iter()
+                .find(|m| m.version == version)
+            {
+                if migration.checksum() != checksum {
+                    return Err(HistoryToDriveError::Unexpected(format!(
+                        "checksum mismatch for migration {} ({}): database has {}, binary has {}",
+                        migration.version,
+                        migration.name,
+                        checksum,
+                        migration.checksum()
+                    )));
+                }
+            }
+            current_version = current_version.max(version);
+        }
+
+        for migration in migrations::postgres::MIGRATIONS
+            .iter()
+            .filter(|m| m.version > current_version)
+        {
+            let mut txn = self.pool.begin().await?;
+            txn.execute(migration.sql).await?;
+            sqlx::query(
+                "insert into history_to_drive.schema_migrations (version, name, checksum) \
+                    values ($1, $2, $3)",
+            )
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .execute(&mut *txn)
+            .await?;
+            txn.commit().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Escapes a single field for Postgres's text `COPY` format: backslash and
+/// the column/row delimiters it would otherwise be mistaken for.
+fn copy_escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[async_trait]
+impl HistoryStore for PostgresStore {
+    async fn ensure_schema(&mut self) -> Result<(), HistoryToDriveError> {
+        self.run_migrations().await
+    }
+
+    /// Matches the `varchar` widths in `migrations/postgres/0001_initial.sql`.
+    fn column_limits(&self) -> ColumnLimits {
+        ColumnLimits {
+            title: Some(1000),
+            host: Some(600),
+            url: Some(3000),
+            user_agent: Some(3000),
+        }
+    }
+
+    async fn last_timestamp(&mut self, origin: &str) -> Result<NaiveDateTime, HistoryToDriveError> {
+        let last_ts: NaiveDateTime = sqlx::query(
+            "\
+            select coalesce(max(h.timestamp), '1970-01-01') last_ts
+            from history_to_drive.history h
+            where h.origin_description = $1",
+        )
+        .bind(origin)
+        .fetch_one(&self.pool)
+        .await
+        .map(|r| r.get("last_ts"))
+        .unwrap_or(chrono::naive::MIN_DATETIME);
+        Ok(last_ts)
+    }
+
+    async fn insert_batch(&mut self, rows: &[HistoryRow]) -> Result<(), HistoryToDriveError> {
+        let mut conn = self.pool.acquire().await?;
+        let mut txn = conn.begin().await?;
+
+        txn.execute(
+            "create temporary table history_staging \
+                (staging_seq bigint, timestamp timestamp, title varchar(1000), \
+                 host varchar(600), url varchar(3000), user_agent varchar(3000), \
+                 origin_description varchar(100)) \
+                on commit drop",
+        )
+        .await?;
+
+        let mut copy = txn
+            .copy_in_raw(
+                "copy history_staging \
+                    (staging_seq, timestamp, title, host, url, user_agent, origin_description) \
+                    from stdin",
+            )
+            .await?;
+
+        let mut buf = String::new();
+        for (i, row) in rows.iter().enumerate() {
+            buf.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                i,
+                row.timestamp.format("%Y-%m-%d %H:%M:%S%.f"),
+                copy_escape(&row.title),
+                copy_escape(&row.host),
+                copy_escape(&row.url),
+                copy_escape(&row.user_agent),
+                copy_escape(&row.origin_description),
+            ));
+        }
+        copy.send(buf.into_bytes()).await?;
+        copy.finish().await?;
+
+        let inserted: Vec<PgRow> = sqlx::query(INSERT_STAGED_HISTORY_SQL)
+            .fetch_all(&mut *txn)
+            .await?;
+
+        let mut history_ids: HashMap<i64, i32> = HashMap::new();
+        for row in &inserted {
+            let history_id: i32 = row.get("history_id");
+            let staging_seq: i64 = row.get("staging_seq");
+            history_ids.insert(staging_seq, history_id);
+        }
+
+        for (i, row) in rows.iter().enumerate() {
+            if row.tags.is_empty() {
+                continue;
+            }
+            if let Some(&history_id) = history_ids.get(&(i as i64)) {
+                sqlx::query(MERGE_TAGS_SQL)
+                    .bind(&row.tags)
+                    .bind(history_id)
+                    .execute(&mut *txn)
+                    .await?;
+            }
+        }
+
+        txn.commit().await?;
+        Ok(())
+    }
+
+    async fn search(
+        &mut self,
+        filters: &SearchFilters,
+    ) -> Result<Vec<HistoryRecord>, HistoryToDriveError> {
+        let rows: Vec<PgRow> = sqlx::query(SEARCH_SQL)
+            .bind(format!(
+                "%{}%",
+                filters.query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+            ))
+            .bind(filters.host)
+            .bind(filters.tag)
+            .bind(filters.after)
+            .bind(filters.before)
+            .bind(filters.limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| HistoryRecord {
+                timestamp: row.get("timestamp"),
+                title: row.get("title"),
+                host: row.get("host"),
+                url: row.get("url"),
+                tags: row.get("tags"),
+            })
+            .collect())
+    }
+}
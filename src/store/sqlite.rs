@@ -0,0 +1,240 @@
+use super::{ColumnLimits, HistoryRecord, HistoryRow, HistoryStore, SearchFilters};
+use crate::error::HistoryToDriveError;
+use crate::migrations;
+use async_trait::async_trait;
+use chrono::naive::NaiveDateTime;
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection};
+
+/// Bootstraps the table `run_migrations` depends on; see the `Migration` doc
+/// for why this can't itself be a numbered migration.
+const CREATE_MIGRATIONS_TABLE_SQL: &str = "\
+    create table if not exists history_to_drive_schema_migrations
+    (
+        version    integer not null primary key,
+        name       text    not null,
+        checksum   integer not null,
+        applied_at text    not null default (datetime('now'))
+    );";
+
+/// Self-contained, server-less `HistoryStore` backed by a local SQLite file.
+/// Intended for people importing their own browser history without standing
+/// up a Postgres instance.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn connect(path: &str) -> Result<Self, HistoryToDriveError> {
+        let conn = Connection::open(path)?;
+        Ok(SqliteStore { conn })
+    }
+
+    fn tag_id(txn: &rusqlite::Transaction, tag: &str) -> Result<i64, HistoryToDriveError> {
+        let existing: Option<i64> = txn
+            .query_row(
+                "select tag_id from history_to_drive_tag where tag = ?1",
+                params![tag],
+                |r| r.get(0),
+            )
+            .ok();
+
+        if let Some(tag_id) = existing {
+            return Ok(tag_id);
+        }
+
+        txn.execute(
+            "insert into history_to_drive_tag (tag) values (?1)",
+            params![tag],
+        )?;
+        Ok(txn.last_insert_rowid())
+    }
+
+    fn tags_for(conn: &Connection, history_id: i64) -> Result<Vec<String>, HistoryToDriveError> {
+        let mut stmt = conn.prepare(
+            "select t.tag from history_to_drive_tag t \
+                join history_to_drive_history_tag ht on ht.tag_id = t.tag_id \
+                where ht.history_id = ?1",
+        )?;
+        let tags = stmt
+            .query_map(params![history_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(tags)
+    }
+}
+
+impl SqliteStore {
+    /// Applies `migrations::sqlite::MIGRATIONS` against `self.conn`; see the
+    /// `Migration` doc for the general behavior.
+    fn run_migrations(&mut self) -> Result<(), HistoryToDriveError> {
+        self.conn.execute_batch(CREATE_MIGRATIONS_TABLE_SQL)?;
+
+        let mut current_version = 0;
+        {
+            let mut stmt = self.conn.prepare(
+                "select version, checksum from history_to_drive_schema_migrations order by version",
+            )?;
+            let mut applied = stmt.query([])?;
+            while let Some(row) = applied.next()? {
+                let version: i32 = row.get(0)?;
+                let checksum: i64 = row.get(1)?;
+                if let Some(migration) = migrations::sqlite::MIGRATIONS
+                    .iter()
+                    .find(|m| m.version == version)
+                {
+                    if migration.checksum() != checksum {
+                        return Err(HistoryToDriveError::Unexpected(format!(
+                            "checksum mismatch for migration {} ({}): database has {}, binary has {}",
+                            migration.version,
+                            migration.name,
+                            checksum,
+                            migration.checksum()
+                        )));
+                    }
+                }
+                current_version = current_version.max(version);
+            }
+        }
+
+        for migration in migrations::sqlite::MIGRATIONS
+            .iter()
+            .filter(|m| m.version > current_version)
+        {
+            let txn = self.conn.transaction()?;
+            txn.execute_batch(migration.sql)?;
+            txn.execute(
+                "insert into history_to_drive_schema_migrations (version, name, checksum) \
+                    values (?1, ?2, ?3)",
+                params![migration.version, migration.name, migration.checksum()],
+            )?;
+            txn.commit()?;
+        }
+
+        Ok(())
+    }
+}
+
+// SQLite is an embedded, single-file database, so these methods run their
+// blocking `rusqlite` calls directly on the async executor rather than via
+// `spawn_blocking` - there is no separate server round-trip to wait on.
+#[async_trait]
+impl HistoryStore for SqliteStore {
+    async fn ensure_schema(&mut self) -> Result<(), HistoryToDriveError> {
+        self.run_migrations()
+    }
+
+    /// `migrations/sqlite/0001_initial.sql` uses unbounded `text` columns.
+    fn column_limits(&self) -> ColumnLimits {
+        ColumnLimits {
+            title: None,
+            host: None,
+            url: None,
+            user_agent: None,
+        }
+    }
+
+    async fn last_timestamp(&mut self, origin: &str) -> Result<NaiveDateTime, HistoryToDriveError> {
+        let last_ts: Option<NaiveDateTime> = self
+            .conn
+            .query_row(
+                "select max(timestamp) from history_to_drive_history where origin_description = ?1",
+                params![origin],
+                |r| r.get(0),
+            )
+            .ok()
+            .flatten();
+
+        Ok(last_ts.unwrap_or(chrono::naive::MIN_DATETIME))
+    }
+
+    async fn insert_batch(&mut self, rows: &[HistoryRow]) -> Result<(), HistoryToDriveError> {
+        let txn = self.conn.transaction()?;
+
+        for row in rows {
+            txn.execute(
+                "insert into history_to_drive_history \
+                    (timestamp, title, host, url, user_agent, origin_description) \
+                    values (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    row.timestamp,
+                    row.title,
+                    row.host,
+                    row.url,
+                    row.user_agent,
+                    row.origin_description
+                ],
+            )?;
+            let history_id = txn.last_insert_rowid();
+
+            for tag in &row.tags {
+                let tag_id = Self::tag_id(&txn, tag)?;
+                txn.execute(
+                    "insert or ignore into history_to_drive_history_tag (history_id, tag_id) \
+                        values (?1, ?2)",
+                    params![history_id, tag_id],
+                )?;
+            }
+        }
+
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn search(
+        &mut self,
+        filters: &SearchFilters,
+    ) -> Result<Vec<HistoryRecord>, HistoryToDriveError> {
+        let mut sql = String::from(
+            "select history_id, timestamp, title, host, url from history_to_drive_history \
+                where (title like ?1 escape '\\' or url like ?1 escape '\\')",
+        );
+        let mut bound: Vec<Box<dyn ToSql>> = vec![Box::new(format!(
+            "%{}%",
+            filters.query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        ))];
+
+        if let Some(host) = filters.host {
+            sql.push_str(&format!(" and host = ?{}", bound.len() + 1));
+            bound.push(Box::new(host.to_string()));
+        }
+        if let Some(tag) = filters.tag {
+            sql.push_str(&format!(
+                " and exists (select 1 from history_to_drive_history_tag ht \
+                    join history_to_drive_tag t on t.tag_id = ht.tag_id \
+                    where ht.history_id = history_to_drive_history.history_id and t.tag = ?{})",
+                bound.len() + 1
+            ));
+            bound.push(Box::new(tag.to_string()));
+        }
+        if let Some(after) = filters.after {
+            sql.push_str(&format!(" and timestamp >= ?{}", bound.len() + 1));
+            bound.push(Box::new(after));
+        }
+        if let Some(before) = filters.before {
+            sql.push_str(&format!(" and timestamp < ?{}", bound.len() + 1));
+            bound.push(Box::new(before));
+        }
+        sql.push_str(&format!(
+            " order by timestamp desc limit ?{}",
+            bound.len() + 1
+        ));
+        bound.push(Box::new(filters.limit));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let params: Vec<&dyn ToSql> = bound.iter().map(|b| b.as_ref()).collect();
+        let mut rows = stmt.query(params.as_slice())?;
+
+        let mut records = Vec::new();
+        while let Some(row) = rows.next()? {
+            let history_id: i64 = row.get(0)?;
+            records.push(HistoryRecord {
+                timestamp: row.get(1)?,
+                title: row.get(2)?,
+                host: row.get(3)?,
+                url: row.get(4)?,
+                tags: Self::tags_for(&self.conn, history_id)?,
+            });
+        }
+        Ok(records)
+    }
+}